@@ -8,17 +8,95 @@
 // You should have received a copy of the MIT License along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use amplify::Slice32;
-use bitcoin::hashes::{sha256t, Hash};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Read, Write};
+use std::num::NonZeroUsize;
+
+use amplify::{Display, Error as AmplifyError, Slice32, Wrapper};
+use ascii_armor::{armor, unarmor};
+use bitcoin::hashes::{sha256, sha256t, Hash};
 use commit_verify::TaggedHash;
 use internet2::addr::ServiceAddr;
-use rgb::MergeReveal;
+use lru::LruCache;
+use rgb::{Anchor, ContractId, Extension, MergeReveal, Transition, TransitionBundle};
 use strict_encoding::{StrictDecode, StrictEncode};
+use url::Url;
 
 use crate::{DaemonError, LaunchError};
 
+/// Default capacity of the in-process read cache sitting in front of the
+/// store backend, used when a node is constructed without an explicit
+/// override.
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// Title line used for the ASCII-armored stash export format produced by
+/// [`Db::export_armored`] and consumed by [`Db::import_armored`].
+const STASH_ARMOR_TITLE: &str = "RGB STASH";
+
+/// Resolves the network location(s) an attachment chunk's data can be
+/// fetched from.
+pub trait AttachmentResolver {
+    /// Returns the candidate URLs to try, in order, for the chunk
+    /// `chunk_hash` belonging to the attachment manifest stored under
+    /// `attachment_id`. Each chunk of a multi-chunk attachment has its
+    /// own content hash and, in general, its own location(s).
+    fn urls(&self, attachment_id: sha256::Hash, chunk_hash: sha256::Hash) -> Vec<Url>;
+}
+
+/// Error verifying or retrieving attachment chunk data in
+/// [`Db::fetch_attachment`].
+#[derive(Clone, Eq, PartialEq, Debug, Display, AmplifyError)]
+#[display(doc_comments)]
+pub enum AttachmentFetchError {
+    /// attachment {0} has no chunk manifest stored locally.
+    UnknownAttachment(sha256::Hash),
+
+    /// none of the resolved URLs for chunk {0} could be fetched.
+    Unreachable(sha256::Hash),
+
+    /// chunk downloaded for hash {0} does not match its expected digest.
+    HashMismatch(sha256::Hash),
+}
+
+/// An object that failed merge-reveal validation, held in the `QUARANTINE`
+/// table for operator inspection instead of aborting the daemon.
+#[derive(Clone, Eq, PartialEq, Debug, StrictEncode, StrictDecode)]
+pub struct QuarantineEntry {
+    /// Table the object was destined for.
+    pub table: String,
+    /// Why `merge_reveal` rejected the object.
+    pub reason: String,
+    /// The contract the object belongs to, so it can be re-indexed on
+    /// [`Db::requeue`].
+    pub contract_key: Slice32,
+    /// The offending object's strict-encoded bytes.
+    pub data: Vec<u8>,
+}
+
+/// The set of object keys belonging to a single contract, keyed in
+/// `CONTRACT_INDEX` by that contract's id. Populated at write time whenever
+/// a transition, anchor, bundle or extension for the contract goes through
+/// [`Db::store_merge`]/[`Db::store_merge_h`] (or the import, sync and
+/// requeue paths built on the same merge); plain [`Db::store`]/[`Db::store_h`]
+/// writes are not recorded. Consulted by [`Db::prune`] to determine what is actually reachable
+/// — `Genesis` itself cannot forward-reference objects created by later
+/// transfers, so the sweep cannot walk from genesis alone.
+#[derive(Clone, Eq, PartialEq, Debug, Default, StrictEncode, StrictDecode)]
+pub struct ContractIndex {
+    pub anchors: BTreeSet<Slice32>,
+    pub bundles: BTreeSet<Slice32>,
+    pub transitions: BTreeSet<Slice32>,
+    pub extensions: BTreeSet<Slice32>,
+}
+
 pub(crate) struct Db {
     pub(crate) store: store_rpc::Client,
+    /// Read-through cache of already strict-encoded table rows, keyed by the
+    /// table name and the object key. Avoids re-hitting `store_rpc::Client`
+    /// for objects (schemata, genesis, etc.) that are read repeatedly while
+    /// validating a consignment; a cache hit still runs `strict_decode` on
+    /// the cached bytes.
+    cache: LruCache<(&'static str, Slice32), Vec<u8>>,
 }
 
 impl Db {
@@ -26,13 +104,30 @@ impl Db {
     pub const BUNDLES: &'static str = "bundles";
     pub const GENESIS: &'static str = "genesis";
     pub const TRANSITIONS: &'static str = "transitions";
-    pub const ANCHORS: &'static str = "transitions";
+    pub const ANCHORS: &'static str = "anchors";
     pub const EXTENSIONS: &'static str = "extensions";
     pub const ATTACHMENT_CHUNKS: &'static str = "chunks";
     pub const ATTACHMENT_INDEX: &'static str = "attachments";
     pub const ALU_LIBS: &'static str = "alu";
+    pub const QUARANTINE: &'static str = "quarantine";
+    pub const CONTRACT_INDEX: &'static str = "contract_index";
+
+    /// Table name `ANCHORS` used before the collision with `TRANSITIONS`
+    /// was fixed (both used to be stored under `"transitions"`). Kept
+    /// around only so [`Db::prune`] can migrate data left behind by nodes
+    /// upgrading from that version.
+    const LEGACY_ANCHORS_TABLE: &'static str = "transitions";
 
     pub fn with(store_endpoint: &ServiceAddr) -> Result<Db, LaunchError> {
+        Db::with_capacity(store_endpoint, NonZeroUsize::new(DEFAULT_CACHE_CAPACITY).expect("const"))
+    }
+
+    /// Same as [`Db::with`], but allows overriding the capacity of the
+    /// in-process read cache placed in front of the store backend.
+    pub fn with_capacity(
+        store_endpoint: &ServiceAddr,
+        cache_capacity: NonZeroUsize,
+    ) -> Result<Db, LaunchError> {
         let mut store = store_rpc::Client::with(store_endpoint).map_err(LaunchError::from)?;
 
         for table in [
@@ -45,11 +140,13 @@ impl Db {
             Db::ATTACHMENT_CHUNKS,
             Db::ATTACHMENT_INDEX,
             Db::ALU_LIBS,
+            Db::QUARANTINE,
+            Db::CONTRACT_INDEX,
         ] {
             store.use_table(table.to_owned()).map_err(LaunchError::from)?;
         }
 
-        Ok(Db { store })
+        Ok(Db { store, cache: LruCache::new(cache_capacity) })
     }
 
     pub fn retrieve<'a, H: 'a + sha256t::Tag, T: StrictDecode>(
@@ -58,11 +155,8 @@ impl Db {
         key: impl TaggedHash<'a, H> + 'a,
     ) -> Result<Option<T>, DaemonError> {
         let slice = key.into_inner();
-        let slice = slice.into_inner();
-        match self.store.retrieve(table.to_owned(), Slice32::from(slice))? {
-            Some(data) => Ok(Some(T::strict_decode(data.as_ref())?)),
-            None => Ok(None),
-        }
+        let slice = Slice32::from(slice.into_inner());
+        self.retrieve_slice(table, slice)
     }
 
     pub fn retrieve_h<T: StrictDecode>(
@@ -70,9 +164,24 @@ impl Db {
         table: &'static str,
         key: impl Hash<Inner = [u8; 32]>,
     ) -> Result<Option<T>, DaemonError> {
-        let slice = *key.as_inner();
-        match self.store.retrieve(table.to_owned(), Slice32::from(slice))? {
-            Some(data) => Ok(Some(T::strict_decode(data.as_ref())?)),
+        let slice = Slice32::from(*key.as_inner());
+        self.retrieve_slice(table, slice)
+    }
+
+    fn retrieve_slice<T: StrictDecode>(
+        &mut self,
+        table: &'static str,
+        slice: Slice32,
+    ) -> Result<Option<T>, DaemonError> {
+        if let Some(data) = self.cache.get(&(table, slice)) {
+            return Ok(Some(T::strict_decode(data.as_slice())?));
+        }
+        match self.store.retrieve(table.to_owned(), slice)? {
+            Some(data) => {
+                let obj = T::strict_decode(data.as_ref())?;
+                self.cache.put((table, slice), data);
+                Ok(Some(obj))
+            }
             None => Ok(None),
         }
     }
@@ -84,9 +193,8 @@ impl Db {
         data: &impl StrictEncode,
     ) -> Result<(), DaemonError> {
         let slice = key.into_inner();
-        let slice = slice.into_inner();
-        self.store.store(table.to_owned(), Slice32::from(slice), data.strict_serialize()?)?;
-        Ok(())
+        let slice = Slice32::from(slice.into_inner());
+        self.store_slice(table, slice, data)
     }
 
     pub fn store_h(
@@ -95,34 +203,594 @@ impl Db {
         key: impl Hash<Inner = [u8; 32]>,
         data: &impl StrictEncode,
     ) -> Result<(), DaemonError> {
-        let slice = *key.as_inner();
-        self.store.store(table.to_owned(), Slice32::from(slice), data.strict_serialize()?)?;
+        let slice = Slice32::from(*key.as_inner());
+        self.store_slice(table, slice, data)
+    }
+
+    fn store_slice(
+        &mut self,
+        table: &'static str,
+        slice: Slice32,
+        data: &impl StrictEncode,
+    ) -> Result<(), DaemonError> {
+        let encoded = data.strict_serialize()?;
+        self.store.store(table.to_owned(), slice, encoded.clone())?;
+        self.cache.put((table, slice), encoded);
         Ok(())
     }
 
+    /// Merge-reveals `new_obj` against whatever is already stored under
+    /// `key`. `contract_id` records which contract the object belongs to
+    /// in `CONTRACT_INDEX`, so [`Db::prune`] can later tell it apart from
+    /// an orphan.
     pub fn store_merge<'a, H: 'a + sha256t::Tag>(
         &mut self,
         table: &'static str,
         key: impl TaggedHash<'a, H> + Copy + 'a,
         new_obj: impl StrictEncode + StrictDecode + MergeReveal + Clone,
+        contract_id: ContractId,
     ) -> Result<(), DaemonError> {
-        let stored_obj = self.retrieve(table, key)?.unwrap_or_else(|| new_obj.clone());
-        let obj = new_obj
-            .merge_reveal(stored_obj)
-            .expect("merge-revealed objects does not match; usually it means hacked database");
-        self.store(Db::GENESIS, key, &obj)
+        let slice = Slice32::from(key.into_inner().into_inner());
+        let contract_key = Slice32::from(contract_id.into_inner().into_inner());
+        self.store_merge_slice(table, slice, new_obj, contract_key)
     }
 
+    /// Same as [`Db::store_merge`], for `Hash`-keyed tables.
     pub fn store_merge_h(
         &mut self,
         table: &'static str,
         key: impl Hash<Inner = [u8; 32]>,
         new_obj: impl StrictEncode + StrictDecode + MergeReveal + Clone,
+        contract_id: ContractId,
+    ) -> Result<(), DaemonError> {
+        let slice = Slice32::from(*key.as_inner());
+        let contract_key = Slice32::from(contract_id.into_inner().into_inner());
+        self.store_merge_slice(table, slice, new_obj, contract_key)
+    }
+
+    fn store_merge_slice<T>(
+        &mut self,
+        table: &'static str,
+        slice: Slice32,
+        new_obj: T,
+        contract_key: Slice32,
+    ) -> Result<(), DaemonError>
+    where
+        T: StrictEncode + StrictDecode + MergeReveal + Clone,
+    {
+        let stored_obj = self.retrieve_slice(table, slice)?.unwrap_or_else(|| new_obj.clone());
+        match new_obj.clone().merge_reveal(stored_obj) {
+            Ok(obj) => {
+                self.store_slice(table, slice, &obj)?;
+                self.index_contract(contract_key, table, slice)
+            }
+            Err(err) => self.quarantine(table, slice, &new_obj, format!("{:?}", err), contract_key),
+        }
+    }
+
+    /// Routes an object that failed merge-reveal into the `QUARANTINE`
+    /// table instead of overwriting or losing it, leaving whatever was
+    /// already stored under `(table, slice)` untouched.
+    fn quarantine(
+        &mut self,
+        table: &'static str,
+        slice: Slice32,
+        obj: &impl StrictEncode,
+        reason: String,
+        contract_key: Slice32,
+    ) -> Result<(), DaemonError> {
+        let entry =
+            QuarantineEntry { table: table.to_owned(), reason, contract_key, data: obj.strict_serialize()? };
+        self.store_slice(Db::QUARANTINE, slice, &entry)
+    }
+
+    /// Records that the object stored under `(table, slice)` belongs to
+    /// `contract_key`, so that [`Db::prune`] can recognize it as reachable.
+    /// A no-op for tables that are not part of the per-contract DAG.
+    fn index_contract(&mut self, contract_key: Slice32, table: &'static str, slice: Slice32) -> Result<(), DaemonError> {
+        let mut index: ContractIndex = self.retrieve_slice(Db::CONTRACT_INDEX, contract_key)?.unwrap_or_default();
+        let changed = match table {
+            t if t == Db::ANCHORS => index.anchors.insert(slice),
+            t if t == Db::BUNDLES => index.bundles.insert(slice),
+            t if t == Db::TRANSITIONS => index.transitions.insert(slice),
+            t if t == Db::EXTENSIONS => index.extensions.insert(slice),
+            _ => false,
+        };
+        if changed {
+            self.store_slice(Db::CONTRACT_INDEX, contract_key, &index)?;
+        }
+        Ok(())
+    }
+
+    /// Lists all objects currently held in quarantine.
+    pub fn quarantined(&mut self) -> Result<Vec<(Slice32, QuarantineEntry)>, DaemonError> {
+        let mut entries = Vec::new();
+        for key in self.keys(Db::QUARANTINE)? {
+            if let Some(entry) = self.retrieve_slice(Db::QUARANTINE, key)? {
+                entries.push((key, entry));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Re-attempts the merge for a quarantined object, removing it from
+    /// quarantine whether or not the retry succeeds (a repeat failure
+    /// simply re-quarantines it). Only objects bound for `TRANSITIONS`,
+    /// `ANCHORS`, `BUNDLES` or `EXTENSIONS` are merge-revealed again; an
+    /// object bound for any other table replaces what is stored there
+    /// as-is.
+    pub fn requeue(&mut self, key: Slice32) -> Result<(), DaemonError> {
+        let entry: Option<QuarantineEntry> = self.retrieve_slice(Db::QUARANTINE, key)?;
+        let Some(entry) = entry else {
+            return Ok(());
+        };
+        self.delete(Db::QUARANTINE, key)?;
+        if let Some(table) = table_by_name(&entry.table) {
+            self.import_record(table, key, &entry.data, entry.contract_key)?;
+        }
+        Ok(())
+    }
+
+    /// Permanently discards a quarantined object without requeuing it.
+    pub fn discard(&mut self, key: Slice32) -> Result<(), DaemonError> {
+        self.delete(Db::QUARANTINE, key)
+    }
+
+    /// Lists all keys currently populated in `table`.
+    pub fn keys(&mut self, table: &'static str) -> Result<Vec<Slice32>, DaemonError> {
+        Ok(self.store.keys(table.to_owned())?)
+    }
+
+    /// Deletes `key` from `table`, evicting it from the read cache too.
+    fn delete(&mut self, table: &'static str, key: Slice32) -> Result<(), DaemonError> {
+        self.store.delete(table.to_owned(), key)?;
+        self.cache.pop(&(table, key));
+        Ok(())
+    }
+
+    /// Mark-and-sweep garbage collection: deletes every `TRANSITIONS`,
+    /// `ANCHORS`, `BUNDLES` and `EXTENSIONS` entry that is not reachable
+    /// from `roots`.
+    ///
+    /// `Genesis` is immutable data fixed at issuance — it cannot
+    /// forward-reference anchors, bundles, transitions or extensions
+    /// created by later transfers, since those don't exist yet when
+    /// genesis is minted. So reachability is not computed by walking
+    /// genesis; instead each root's `CONTRACT_INDEX` entry (populated at
+    /// write time by [`Db::store_merge`]/[`Db::store_merge_h`]) is taken
+    /// as the reachable set directly. `GENESIS` itself is never pruned,
+    /// since a contract's own root is always live once referenced.
+    ///
+    /// Before sweeping, moves any anchor still sitting in the legacy
+    /// shared `"transitions"` table that predates the anchors/transitions
+    /// table-name collision fix into `ANCHORS`. A migrated anchor has no
+    /// `CONTRACT_INDEX` entry of its own yet (the legacy table never
+    /// recorded one), so it is excluded from this run's sweep entirely.
+    /// Later runs treat it like any other anchor: it is kept only if a
+    /// root's `CONTRACT_INDEX` lists it, which happens once it is touched
+    /// by [`Db::store_merge`]/[`Db::store_merge_h`].
+    ///
+    /// Returns the number of keys removed.
+    pub fn prune(&mut self, roots: impl IntoIterator<Item = ContractId>) -> Result<usize, DaemonError> {
+        let migrated_anchors = self.migrate_legacy_anchors()?;
+
+        let mut reachable: HashMap<&'static str, HashSet<Slice32>> = HashMap::new();
+        for contract_id in roots {
+            self.mark_reachable(contract_id, &mut reachable)?;
+        }
+
+        let mut pruned = 0usize;
+        for table in [Db::TRANSITIONS, Db::ANCHORS, Db::BUNDLES, Db::EXTENSIONS] {
+            let live = reachable.get(table);
+            for key in self.keys(table)? {
+                if should_sweep(table, &key, live, &migrated_anchors) {
+                    self.delete(table, key)?;
+                    pruned += 1;
+                }
+            }
+        }
+        Ok(pruned)
+    }
+
+    fn mark_reachable(
+        &mut self,
+        contract_id: ContractId,
+        reachable: &mut HashMap<&'static str, HashSet<Slice32>>,
+    ) -> Result<(), DaemonError> {
+        let contract_key = Slice32::from(contract_id.into_inner().into_inner());
+        let index: ContractIndex = self.retrieve_slice(Db::CONTRACT_INDEX, contract_key)?.unwrap_or_default();
+        reachable.entry(Db::ANCHORS).or_default().extend(index.anchors);
+        reachable.entry(Db::BUNDLES).or_default().extend(index.bundles);
+        reachable.entry(Db::TRANSITIONS).or_default().extend(index.transitions);
+        reachable.entry(Db::EXTENSIONS).or_default().extend(index.extensions);
+        Ok(())
+    }
+
+    /// One-time migration for nodes upgrading from the version where
+    /// `ANCHORS` and `TRANSITIONS` collided on the same backend table
+    /// name (`"transitions"`). Walks that legacy shared table and moves
+    /// every row that decodes as an `Anchor` (and not as a `Transition`)
+    /// into the now-distinct `ANCHORS` table, leaving genuine transitions
+    /// untouched. A no-op once the legacy table has been fully migrated.
+    ///
+    /// Returns the set of migrated keys. The legacy table never recorded
+    /// a `CONTRACT_INDEX` entry for them, so the caller must not let
+    /// [`Db::prune`]'s sweep treat a migrated-but-unindexed anchor as an
+    /// orphan.
+    fn migrate_legacy_anchors(&mut self) -> Result<HashSet<Slice32>, DaemonError> {
+        let mut migrated = HashSet::new();
+        for key in self.keys(Db::LEGACY_ANCHORS_TABLE)? {
+            let Some(data) = self.store.retrieve(Db::LEGACY_ANCHORS_TABLE.to_owned(), key)? else {
+                continue;
+            };
+            let looks_like_anchor =
+                Anchor::strict_decode(data.as_slice()).is_ok() && Transition::strict_decode(data.as_slice()).is_err();
+            if looks_like_anchor {
+                self.store_bytes_slice(Db::ANCHORS, key, data)?;
+                self.delete(Db::LEGACY_ANCHORS_TABLE, key)?;
+                migrated.insert(key);
+            }
+        }
+        Ok(migrated)
+    }
+
+    /// Exports `tables` as a single ASCII-armored blob: a backend-agnostic
+    /// backup/transfer format for the stash. Each row is framed as `table
+    /// name | key | owning contract key | strict-encoded value` (the
+    /// contract key lets the importer rebuild `CONTRACT_INDEX`; it is the
+    /// zero key for tables that aren't part of the per-contract DAG), and
+    /// the whole payload is wrapped with a checksum header by the armor so
+    /// transport corruption is caught on import instead of silently
+    /// accepted.
+    pub fn export_armored(
+        &mut self,
+        tables: &[&'static str],
+        mut writer: impl Write,
     ) -> Result<(), DaemonError> {
-        let stored_obj = self.retrieve_h(table, key)?.unwrap_or_else(|| new_obj.clone());
-        let obj = new_obj
-            .merge_reveal(stored_obj)
-            .expect("merge-revealed objects does not match; usually it means hacked database");
-        self.store_h(Db::GENESIS, key, &obj)
+        let reverse_index = reverse_contract_index(&mut self.store)?;
+
+        let mut payload = Vec::new();
+        for table in tables {
+            for key in self.keys(table)? {
+                if let Some(data) = self.store.retrieve((*table).to_owned(), key)? {
+                    let contract_key = reverse_index.get(&key).copied().unwrap_or(zero_contract_key());
+                    write_record(&mut payload, table, key, contract_key, &data);
+                }
+            }
+        }
+        writer.write_all(armor(STASH_ARMOR_TITLE, &payload).as_bytes())?;
+        Ok(())
+    }
+
+    /// Imports a stash produced by [`Db::export_armored`]. Transitions,
+    /// bundles, anchors and extensions are merge-revealed against whatever
+    /// is already stored under the same key (so concurrent partial
+    /// knowledge combines rather than one copy clobbering the other) and
+    /// re-indexed under their recorded contract key; all other tables are
+    /// re-inserted as-is.
+    pub fn import_armored(&mut self, mut reader: impl Read) -> Result<(), DaemonError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let payload = unarmor(&text)?;
+
+        let mut rest = payload.as_slice();
+        while let Some((table, key, contract_key, data, remaining)) = read_record(rest) {
+            if let Some(table) = table_by_name(table) {
+                self.import_record(table, key, data, contract_key)?;
+            }
+            rest = remaining;
+        }
+        Ok(())
+    }
+
+    fn import_record(
+        &mut self,
+        table: &'static str,
+        key: Slice32,
+        data: &[u8],
+        contract_key: Slice32,
+    ) -> Result<(), DaemonError> {
+        match table {
+            t if t == Db::TRANSITIONS => {
+                self.store_merge_slice(Db::TRANSITIONS, key, Transition::strict_decode(data)?, contract_key)
+            }
+            t if t == Db::ANCHORS => {
+                self.store_merge_slice(Db::ANCHORS, key, Anchor::strict_decode(data)?, contract_key)
+            }
+            t if t == Db::BUNDLES => {
+                self.store_merge_slice(Db::BUNDLES, key, TransitionBundle::strict_decode(data)?, contract_key)
+            }
+            t if t == Db::EXTENSIONS => {
+                self.store_merge_slice(Db::EXTENSIONS, key, Extension::strict_decode(data)?, contract_key)
+            }
+            _ => self.store_bytes_slice(table, key, data.to_vec()),
+        }
+    }
+
+    fn store_bytes_slice(&mut self, table: &'static str, slice: Slice32, data: Vec<u8>) -> Result<(), DaemonError> {
+        self.store.store(table.to_owned(), slice, data.clone())?;
+        self.cache.put((table, slice), data);
+        Ok(())
+    }
+
+    /// Replicates stash objects from a remote RGB node's store.
+    ///
+    /// Connects to `peer` over the same `ServiceAddr`/`store_rpc`
+    /// machinery used for the local store. Keys the remote has that we
+    /// don't are fetched and imported directly (the original
+    /// set-difference fast path). For keys present on both sides — where
+    /// one side may hold only a partial reveal — the remote value is
+    /// still fetched, but it is only passed to [`Db::import_record`] when
+    /// its bytes differ from what is already stored (merge-revealed for
+    /// transitions, anchors, bundles and extensions, written over the
+    /// local row as-is for every other table). A byte-identical key still
+    /// has its `CONTRACT_INDEX` entry reconciled via [`Db::index_contract`]
+    /// (e.g. a legacy-migrated anchor the remote already has indexed), so
+    /// a converged key never becomes GC-orphaned purely for having skipped
+    /// the write. Returns the number of remote objects passed to
+    /// [`Db::import_record`], including any that end up quarantined.
+    pub fn sync_from(&mut self, peer: &ServiceAddr, tables: &[&'static str]) -> Result<usize, DaemonError> {
+        let mut remote = store_rpc::Client::with(peer).map_err(DaemonError::from)?;
+        let remote_index = reverse_contract_index(&mut remote)?;
+
+        let mut synced = 0usize;
+        for table in tables {
+            remote.use_table((*table).to_owned()).map_err(DaemonError::from)?;
+
+            let local_keys: HashSet<Slice32> = self.keys(table)?.into_iter().collect();
+
+            for key in remote.keys((*table).to_owned())? {
+                let Some(data) = remote.retrieve((*table).to_owned(), key)? else {
+                    continue;
+                };
+
+                let contract_key = remote_index.get(&key).copied().unwrap_or(zero_contract_key());
+
+                if local_keys.contains(&key) {
+                    let local_data = self.store.retrieve((*table).to_owned(), key)?;
+                    if local_data.as_deref() == Some(data.as_slice()) {
+                        if contract_key != zero_contract_key() {
+                            self.index_contract(contract_key, table, key)?;
+                        }
+                        continue;
+                    }
+                }
+
+                self.import_record(table, key, &data, contract_key)?;
+                synced += 1;
+            }
+        }
+        Ok(synced)
+    }
+
+    /// Fetches the chunks of an attachment whose hash is known but whose
+    /// bytes are not yet present locally.
+    ///
+    /// Looks up the chunk manifest under `attachment_id` in
+    /// `ATTACHMENT_INDEX`, downloads each missing chunk from the URLs
+    /// `resolver` returns, and verifies every downloaded chunk against its
+    /// expected sha256 key. Verified chunks are buffered in memory and only
+    /// persisted into `ATTACHMENT_CHUNKS` once the *entire* manifest has
+    /// passed verification, so a hash mismatch partway through truly
+    /// rejects the whole fetch instead of leaving earlier chunks committed.
+    pub fn fetch_attachment(
+        &mut self,
+        attachment_id: sha256::Hash,
+        resolver: &impl AttachmentResolver,
+    ) -> Result<(), DaemonError> {
+        let manifest: Vec<sha256::Hash> = self
+            .retrieve_h(Db::ATTACHMENT_INDEX, attachment_id)?
+            .ok_or(AttachmentFetchError::UnknownAttachment(attachment_id))?;
+
+        let mut verified = Vec::new();
+        for chunk_hash in manifest {
+            let chunk_slice = Slice32::from(*chunk_hash.as_inner());
+            if self.store.retrieve(Db::ATTACHMENT_CHUNKS.to_owned(), chunk_slice)?.is_some() {
+                continue;
+            }
+
+            let data = resolver
+                .urls(attachment_id, chunk_hash)
+                .into_iter()
+                .find_map(|url| fetch_url(&url))
+                .ok_or(AttachmentFetchError::Unreachable(chunk_hash))?;
+
+            if sha256::Hash::hash(&data) != chunk_hash {
+                return Err(AttachmentFetchError::HashMismatch(chunk_hash).into());
+            }
+
+            verified.push((chunk_slice, data));
+        }
+
+        for (chunk_slice, data) in verified {
+            self.store_bytes_slice(Db::ATTACHMENT_CHUNKS, chunk_slice, data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sentinel contract key written for rows that aren't part of any
+/// per-contract DAG (schemata, genesis, attachment tables, etc.), so the
+/// wire frame always carries a fixed-size key field.
+fn zero_contract_key() -> Slice32 { Slice32::from([0u8; 32]) }
+
+/// Scans `CONTRACT_INDEX` on `store` and inverts it into an `object key ->
+/// contract key` map, so export/sync can attach the owning contract to
+/// object rows that don't otherwise carry that association on the wire.
+fn reverse_contract_index(store: &mut store_rpc::Client) -> Result<HashMap<Slice32, Slice32>, DaemonError> {
+    let mut reverse = HashMap::new();
+    for contract_key in store.keys(Db::CONTRACT_INDEX.to_owned())? {
+        let Some(data) = store.retrieve(Db::CONTRACT_INDEX.to_owned(), contract_key)? else {
+            continue;
+        };
+        let index = ContractIndex::strict_decode(data.as_slice())?;
+        for key in index.anchors.iter().chain(&index.bundles).chain(&index.transitions).chain(&index.extensions) {
+            reverse.insert(*key, contract_key);
+        }
+    }
+    Ok(reverse)
+}
+
+/// Downloads `url` and returns its body, or `None` on any transport error.
+fn fetch_url(url: &Url) -> Option<Vec<u8>> {
+    let response = ureq::get(url.as_str()).call().ok()?;
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// Appends a single framed `(table, key, contract key, value)` record to
+/// `buf`.
+fn write_record(buf: &mut Vec<u8>, table: &str, key: Slice32, contract_key: Slice32, data: &[u8]) {
+    buf.push(table.len() as u8);
+    buf.extend_from_slice(table.as_bytes());
+    buf.extend_from_slice(key.as_inner());
+    buf.extend_from_slice(contract_key.as_inner());
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Parses a single framed record off the front of `buf`, returning the
+/// table name, key, owning contract key, value and the remaining unparsed
+/// tail.
+fn read_record(buf: &[u8]) -> Option<(&str, Slice32, Slice32, &[u8], &[u8])> {
+    let (&table_len, buf) = buf.split_first()?;
+    let table_len = table_len as usize;
+    if buf.len() < table_len + 32 + 32 + 4 {
+        return None;
+    }
+    let (table, buf) = buf.split_at(table_len);
+    let table = std::str::from_utf8(table).ok()?;
+    let (key, buf) = buf.split_at(32);
+    let mut key_bytes = [0u8; 32];
+    key_bytes.copy_from_slice(key);
+    let key = Slice32::from(key_bytes);
+    let (contract_key, buf) = buf.split_at(32);
+    let mut contract_key_bytes = [0u8; 32];
+    contract_key_bytes.copy_from_slice(contract_key);
+    let contract_key = Slice32::from(contract_key_bytes);
+    let (len, buf) = buf.split_at(4);
+    let data_len = u32::from_le_bytes(len.try_into().ok()?) as usize;
+    if buf.len() < data_len {
+        return None;
+    }
+    let (data, buf) = buf.split_at(data_len);
+    Some((table, key, contract_key, data, buf))
+}
+
+/// Resolves a table name read off the wire back to the matching `&'static
+/// str` constant used throughout [`Db`]. Covers every table
+/// [`Db::export_armored`] is able to serialize, including `QUARANTINE`
+/// and `CONTRACT_INDEX`, so round-tripping a stash through
+/// [`Db::import_armored`] never silently drops rows from those tables
+/// (which would otherwise make the very next [`Db::prune`] treat
+/// everything as unreachable).
+fn table_by_name(name: &str) -> Option<&'static str> {
+    [
+        Db::SCHEMATA,
+        Db::BUNDLES,
+        Db::GENESIS,
+        Db::TRANSITIONS,
+        Db::ANCHORS,
+        Db::EXTENSIONS,
+        Db::ATTACHMENT_CHUNKS,
+        Db::ATTACHMENT_INDEX,
+        Db::ALU_LIBS,
+        Db::QUARANTINE,
+        Db::CONTRACT_INDEX,
+    ]
+    .into_iter()
+    .find(|t| *t == name)
+}
+
+/// True if `key` is not present in `live` (the reachable set for its
+/// table, or `None` if no root touched that table at all), i.e. it is a
+/// candidate for [`Db::prune`] to delete.
+fn is_orphaned(live: Option<&HashSet<Slice32>>, key: &Slice32) -> bool {
+    !live.map(|set| set.contains(key)).unwrap_or(false)
+}
+
+/// True if [`Db::prune`] should delete `key` from `table` this run: it
+/// must be orphaned, and — for `ANCHORS` — must not have just been
+/// migrated out of the legacy shared table by
+/// [`Db::migrate_legacy_anchors`], since a freshly migrated anchor has no
+/// `CONTRACT_INDEX` entry yet and would otherwise read as an orphan.
+fn should_sweep(
+    table: &'static str,
+    key: &Slice32,
+    live: Option<&HashSet<Slice32>>,
+    migrated_anchors: &HashSet<Slice32>,
+) -> bool {
+    if table == Db::ANCHORS && migrated_anchors.contains(key) {
+        return false;
+    }
+    is_orphaned(live, key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_round_trips_through_write_and_read() {
+        let mut buf = Vec::new();
+        let key = Slice32::from([7u8; 32]);
+        let contract_key = Slice32::from([9u8; 32]);
+        write_record(&mut buf, Db::TRANSITIONS, key, contract_key, b"payload");
+
+        let (table, read_key, read_contract_key, data, rest) = read_record(&buf).expect("record parses");
+        assert_eq!(table, Db::TRANSITIONS);
+        assert_eq!(read_key, key);
+        assert_eq!(read_contract_key, contract_key);
+        assert_eq!(data, b"payload");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn read_record_rejects_truncated_input() {
+        let mut buf = Vec::new();
+        write_record(&mut buf, Db::ANCHORS, Slice32::from([1u8; 32]), Slice32::from([2u8; 32]), b"x");
+        buf.truncate(buf.len() - 1);
+        assert!(read_record(&buf).is_none());
+    }
+
+    #[test]
+    fn table_by_name_resolves_known_tables_only() {
+        assert_eq!(table_by_name(Db::ANCHORS), Some(Db::ANCHORS));
+        assert_eq!(table_by_name(Db::TRANSITIONS), Some(Db::TRANSITIONS));
+        assert_eq!(table_by_name(Db::QUARANTINE), Some(Db::QUARANTINE));
+        assert_eq!(table_by_name(Db::CONTRACT_INDEX), Some(Db::CONTRACT_INDEX));
+        assert_eq!(table_by_name("not-a-real-table"), None);
+    }
+
+    #[test]
+    fn is_orphaned_when_no_root_touched_the_table() {
+        assert!(is_orphaned(None, &Slice32::from([3u8; 32])));
+    }
+
+    #[test]
+    fn is_orphaned_when_key_outside_reachable_set() {
+        let mut live = HashSet::new();
+        live.insert(Slice32::from([3u8; 32]));
+        assert!(is_orphaned(Some(&live), &Slice32::from([4u8; 32])));
+        assert!(!is_orphaned(Some(&live), &Slice32::from([3u8; 32])));
+    }
+
+    #[test]
+    fn should_sweep_spares_a_migrated_but_unindexed_anchor() {
+        let key = Slice32::from([5u8; 32]);
+        let mut migrated = HashSet::new();
+        migrated.insert(key);
+
+        // No root's CONTRACT_INDEX mentions this key (it was never
+        // indexed under the old collided table), so a naive orphan check
+        // would delete it. should_sweep must spare it instead.
+        assert!(!should_sweep(Db::ANCHORS, &key, None, &migrated));
+
+        // Once it's no longer in the migrated set (any later prune run),
+        // the usual reachability rules apply again: still unindexed, it
+        // is swept.
+        assert!(should_sweep(Db::ANCHORS, &key, None, &HashSet::new()));
     }
 }
\ No newline at end of file